@@ -0,0 +1,251 @@
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+use std::io;
+use std::marker::PhantomData;
+use std::path::Path;
+
+/// Sidecar file magic for a persisted [`BloomFilter`].
+const SIDECAR_MAGIC: [u8; 8] = *b"CSBLOOM1";
+const ITEMS_COUNT_SIZE: usize = std::mem::size_of::<u64>();
+const FP_P_SIZE: usize = std::mem::size_of::<f64>();
+const BIT_COUNT_SIZE: usize = std::mem::size_of::<u64>();
+const K_SIZE: usize = std::mem::size_of::<u32>();
+const SIDECAR_HEADER_SIZE: usize =
+    SIDECAR_MAGIC.len() + ITEMS_COUNT_SIZE + FP_P_SIZE + BIT_COUNT_SIZE + K_SIZE;
+
+/// A counting Bloom filter sized for a target false-positive rate.
+///
+/// Unlike a plain bitset Bloom filter, each slot is a saturating `u8`
+/// counter rather than a single bit, which lets [`unset`](Self::unset)
+/// actually retract a key instead of leaving `check` stuck reporting `true`
+/// forever.
+///
+/// Invariant: once a counter saturates at `u8::MAX` it must never be
+/// decremented again, even by a later `unset`. A saturated counter is shared
+/// by every key that ever hashed to it, so we can no longer tell whether
+/// decrementing it would zero it out from under a key that is still
+/// present. Such a counter is simply left at `u8::MAX` and behaves like a
+/// classic (non-counting) Bloom filter bit from then on.
+#[derive(Debug, Clone)]
+pub struct BloomFilter<K> {
+    counters: Vec<u8>,
+    bit_count: u64,
+    k: u32,
+    items_count: usize,
+    fp_p: f64,
+    _key: PhantomData<K>,
+}
+
+impl<K: Hash> BloomFilter<K> {
+    /// Build a filter sized to hold `items_count` items at a false-positive
+    /// rate of `fp_p`.
+    pub fn new_for_fp_rate(items_count: usize, fp_p: f64) -> Self {
+        let bit_count = Self::optimal_bit_count(items_count, fp_p);
+        let k = Self::optimal_k(items_count, bit_count);
+        Self {
+            counters: vec![0u8; bit_count.max(1) as usize],
+            bit_count: bit_count.max(1),
+            k,
+            items_count,
+            fp_p,
+            _key: PhantomData,
+        }
+    }
+
+    fn optimal_bit_count(items_count: usize, fp_p: f64) -> u64 {
+        let n = items_count.max(1) as f64;
+        let m = -(n * fp_p.ln()) / (std::f64::consts::LN_2 * std::f64::consts::LN_2);
+        m.ceil() as u64
+    }
+
+    fn optimal_k(items_count: usize, bit_count: u64) -> u32 {
+        let n = items_count.max(1) as f64;
+        let k = (bit_count as f64 / n) * std::f64::consts::LN_2;
+        k.round().max(1.0) as u32
+    }
+
+    fn indexes(&self, key: &K) -> impl Iterator<Item = u64> + '_ {
+        let mut hasher1 = DefaultHasher::new();
+        key.hash(&mut hasher1);
+        let h1 = hasher1.finish();
+
+        let mut hasher2 = DefaultHasher::new();
+        h1.hash(&mut hasher2);
+        let h2 = hasher2.finish();
+
+        let bit_count = self.bit_count;
+        (0..self.k).map(move |i| h1.wrapping_add((i as u64).wrapping_mul(h2)) % bit_count)
+    }
+
+    /// Mark `key` as present, incrementing each of its `k` counters.
+    pub fn set(&mut self, key: &K) {
+        for idx in self.indexes(key).collect::<Vec<_>>() {
+            let counter = &mut self.counters[idx as usize];
+            *counter = counter.saturating_add(1);
+        }
+    }
+
+    /// Retract `key`, decrementing each of its `k` counters.
+    ///
+    /// Counters that have saturated at `u8::MAX` are left untouched (see the
+    /// type-level invariant), so a key that collided with enough other keys
+    /// to saturate one of its slots may continue to `check` as present even
+    /// after `unset` — exactly as a classic Bloom filter would.
+    pub fn unset(&mut self, key: &K) {
+        for idx in self.indexes(key).collect::<Vec<_>>() {
+            let counter = &mut self.counters[idx as usize];
+            if *counter > 0 && *counter < u8::MAX {
+                *counter -= 1;
+            }
+        }
+    }
+
+    /// Returns `true` if `key` may be present, `false` if it is definitely not.
+    pub fn check(&self, key: &K) -> bool {
+        self.indexes(key).all(|idx| self.counters[idx as usize] != 0)
+    }
+
+    /// Dump the raw counters plus the parameters this filter was built for
+    /// to `path`, so a later [`load_from`](Self::load_from) can restore it
+    /// without rescanning the backing store.
+    pub fn save_to(&self, path: &Path) -> io::Result<()> {
+        let mut bytes = Vec::with_capacity(SIDECAR_HEADER_SIZE + self.counters.len());
+        bytes.extend_from_slice(&SIDECAR_MAGIC);
+        bytes.extend_from_slice(&(self.items_count as u64).to_le_bytes());
+        bytes.extend_from_slice(&self.fp_p.to_le_bytes());
+        bytes.extend_from_slice(&self.bit_count.to_le_bytes());
+        bytes.extend_from_slice(&self.k.to_le_bytes());
+        bytes.extend_from_slice(&self.counters);
+        std::fs::write(path, bytes)
+    }
+
+    /// Restore a filter previously written by [`save_to`](Self::save_to).
+    ///
+    /// Returns `Ok(None)` (rather than an error) whenever the sidecar can't
+    /// be trusted as-is: it is absent, truncated, has a different magic, or
+    /// was built for a different `items_count`/`fp_p` than requested here.
+    /// Callers should treat that as a signal to rebuild the filter from the
+    /// backing store instead of failing outright.
+    pub fn load_from(path: &Path, items_count: usize, fp_p: f64) -> io::Result<Option<Self>> {
+        let bytes = match std::fs::read(path) {
+            Ok(bytes) => bytes,
+            Err(error) if error.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(error) => return Err(error),
+        };
+
+        if bytes.len() < SIDECAR_HEADER_SIZE || bytes[..SIDECAR_MAGIC.len()] != SIDECAR_MAGIC {
+            return Ok(None);
+        }
+
+        let mut offset = SIDECAR_MAGIC.len();
+        let stored_items_count = u64::from_le_bytes(
+            bytes[offset..offset + ITEMS_COUNT_SIZE].try_into().unwrap(),
+        ) as usize;
+        offset += ITEMS_COUNT_SIZE;
+        let stored_fp_p = f64::from_le_bytes(bytes[offset..offset + FP_P_SIZE].try_into().unwrap());
+        offset += FP_P_SIZE;
+        let bit_count =
+            u64::from_le_bytes(bytes[offset..offset + BIT_COUNT_SIZE].try_into().unwrap());
+        offset += BIT_COUNT_SIZE;
+        let k = u32::from_le_bytes(bytes[offset..offset + K_SIZE].try_into().unwrap());
+
+        if stored_items_count != items_count || stored_fp_p != fp_p {
+            return Ok(None);
+        }
+
+        let counters = &bytes[SIDECAR_HEADER_SIZE..];
+        if counters.len() as u64 != bit_count {
+            return Ok(None);
+        }
+
+        Ok(Some(Self {
+            counters: counters.to_vec(),
+            bit_count,
+            k,
+            items_count,
+            fp_p,
+            _key: PhantomData,
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn set_then_unset_clears_the_key() {
+        let mut filter: BloomFilter<&str> = BloomFilter::new_for_fp_rate(100, 0.01);
+        filter.set(&"a");
+        assert!(filter.check(&"a"));
+        assert!(!filter.check(&"b"));
+
+        filter.unset(&"a");
+        assert!(!filter.check(&"a"));
+    }
+
+    #[test]
+    fn a_saturated_counter_stays_present_after_unset() {
+        let mut filter: BloomFilter<&str> = BloomFilter::new_for_fp_rate(1, 0.5);
+        for _ in 0..=u8::MAX as u32 {
+            filter.set(&"a");
+        }
+
+        filter.unset(&"a");
+        // Setting "a" 256 times saturates its counters, and a saturated
+        // counter is never decremented, so "a" keeps checking present.
+        assert!(filter.check(&"a"));
+    }
+
+    #[test]
+    fn unset_of_an_absent_key_does_not_underflow() {
+        let mut filter: BloomFilter<&str> = BloomFilter::new_for_fp_rate(100, 0.01);
+        filter.unset(&"never-set");
+        assert!(!filter.check(&"never-set"));
+    }
+
+    fn temp_path(name: &str) -> std::path::PathBuf {
+        let nanos = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .expect("system clock is after the epoch")
+            .as_nanos();
+        std::env::temp_dir().join(format!("cache-syncer-bloom-{name}-{nanos}"))
+    }
+
+    #[test]
+    fn save_to_then_load_from_round_trips() {
+        let path = temp_path("round-trip");
+        let mut filter: BloomFilter<&str> = BloomFilter::new_for_fp_rate(100, 0.01);
+        filter.set(&"a");
+        filter.set(&"b");
+        filter.save_to(&path).unwrap();
+
+        let loaded = BloomFilter::<&str>::load_from(&path, 100, 0.01)
+            .unwrap()
+            .unwrap();
+        assert!(loaded.check(&"a"));
+        assert!(loaded.check(&"b"));
+        assert!(!loaded.check(&"c"));
+
+        std::fs::remove_file(&path).ok();
+    }
+
+    #[test]
+    fn load_from_rejects_a_sidecar_built_for_different_parameters() {
+        let path = temp_path("mismatch");
+        let filter: BloomFilter<&str> = BloomFilter::new_for_fp_rate(100, 0.01);
+        filter.save_to(&path).unwrap();
+
+        let loaded = BloomFilter::<&str>::load_from(&path, 200, 0.01).unwrap();
+        assert!(loaded.is_none());
+
+        std::fs::remove_file(&path).ok();
+    }
+
+    #[test]
+    fn load_from_a_missing_sidecar_returns_none_rather_than_erroring() {
+        let path = temp_path("missing");
+        let loaded = BloomFilter::<&str>::load_from(&path, 100, 0.01).unwrap();
+        assert!(loaded.is_none());
+    }
+}
@@ -0,0 +1,22 @@
+/// A bounded, weight-aware in-memory cache used as the hot tier in front of
+/// a [`DiskCache`](crate::DiskCache).
+pub trait Cache<T>: Default {
+    /// Insert `item`, evicting existing entries as needed to respect `weight`.
+    fn insert_with_weight(&mut self, item: T, weight: usize);
+
+    /// Find the first entry matching `predicate`.
+    fn find<F>(&mut self, predicate: F) -> Option<&T>
+    where
+        F: FnMut(&T) -> bool;
+
+    /// Look up the first entry matching `predicate`, promoting it if the
+    /// underlying cache tracks recency (e.g. an LRU).
+    fn lookup<F>(&mut self, predicate: F) -> Option<&T>
+    where
+        F: FnMut(&T) -> bool;
+
+    /// Remove and return the first entry matching `predicate`, if any.
+    fn remove<F>(&mut self, predicate: F) -> Option<T>
+    where
+        F: FnMut(&T) -> bool;
+}
@@ -2,6 +2,7 @@ use crate::{BloomFilter, Cache, DiskCache};
 use std::fmt::Debug;
 use std::hash::Hash;
 use std::marker::PhantomData;
+use std::path::PathBuf;
 use tracing::trace;
 
 pub struct DefaultCacher<K: Eq, V, C, D: DiskCache<K, V>> {
@@ -10,7 +11,14 @@ pub struct DefaultCacher<K: Eq, V, C, D: DiskCache<K, V>> {
     pub disk_cache: D,
 
     requested: u128,
-    in_hotcache: u128,
+    hot_cache_hits: u128,
+    bloom_rejected: u128,
+    disk_hits: u128,
+    disk_misses: u128,
+
+    /// Where [`flush_bloom_filter`](Self::flush_bloom_filter) persists the
+    /// Bloom filter, if this cacher was built with one.
+    bloom_filter_path: Option<PathBuf>,
 
     _v: PhantomData<V>,
 }
@@ -30,7 +38,11 @@ where
             hot_cache,
             disk_cache,
             requested: 0,
-            in_hotcache: 0,
+            hot_cache_hits: 0,
+            bloom_rejected: 0,
+            disk_hits: 0,
+            disk_misses: 0,
+            bloom_filter_path: None,
 
             _v: PhantomData,
         }
@@ -47,11 +59,54 @@ where
         Ok(cacher)
     }
 
+    /// Create a cacher whose Bloom filter is persisted at `bloom_filter_path`.
+    ///
+    /// If a sidecar written by a previous [`flush_bloom_filter`](Self::flush_bloom_filter)
+    /// is present and was built for the same `items_count`/`fp_p`, it is
+    /// loaded as-is. Otherwise this falls back to [`init_bloom_filter`](Self::init_bloom_filter),
+    /// the full disk scan. A loaded sidecar is not checked against what is
+    /// on disk: it reflects the keys as of its last flush.
+    pub async fn new_and_load_bloom(
+        disk_cache: D,
+        items_count: usize,
+        fp_p: f64,
+        bloom_filter_path: PathBuf,
+    ) -> anyhow::Result<Self> {
+        let mut cacher = Self::new(disk_cache, items_count, fp_p);
+        match BloomFilter::load_from(&bloom_filter_path, items_count, fp_p)? {
+            Some(bloom_filter) => {
+                trace!(path = ?bloom_filter_path, "Loaded persisted bloom filter");
+                cacher.bloom_filter = bloom_filter;
+            }
+            None => cacher.init_bloom_filter().await?,
+        }
+        cacher.bloom_filter_path = Some(bloom_filter_path);
+        Ok(cacher)
+    }
+
+    /// Persist the Bloom filter to the path given to [`new_and_load_bloom`](Self::new_and_load_bloom),
+    /// if any. A no-op for cachers built without a persistence path.
+    pub fn flush_bloom_filter(&self) -> anyhow::Result<()> {
+        if let Some(path) = &self.bloom_filter_path {
+            self.bloom_filter.save_to(path)?;
+        }
+        Ok(())
+    }
+
     pub async fn store(&mut self, key: K, value: V, weight: usize) -> anyhow::Result<()> {
-        // set bloom filter
-        self.bloom_filter.set(&key);
-        // store into disk
-        self.disk_cache.store(&key, value.clone()).await?;
+        // Only `set` the bloom filter on this key's first insertion: a
+        // counting filter's counters only balance against a single `unset`
+        // per key, so re-storing an already-cached key must not bump them
+        // again or a later `remove` would leave it stuck reporting present.
+        let is_new = !self.disk_cache.exist(&key).await;
+        if is_new {
+            self.bloom_filter.set(&key);
+        }
+        // store into disk, unsetting the bloom filter for any key this
+        // overwrite evicted from disk as a side effect
+        if let Some(evicted) = self.disk_cache.store(&key, value.clone()).await? {
+            self.bloom_filter.unset(&evicted);
+        }
         // insert into hot cache
         self.hot_cache
             .insert_with_weight(CacheEntry::new(key, value), weight);
@@ -59,6 +114,17 @@ where
         Ok(())
     }
 
+    /// Remove `key` from disk, the hot cache, and the bloom filter.
+    pub async fn remove(&mut self, key: &K) -> anyhow::Result<()> {
+        self.disk_cache.remove(key).await?;
+        // `store` and `load_from_disk` can each leave an entry for `key`, so
+        // drop every one of them, not just the first.
+        while self.hot_cache.remove(|item| &item.key == key).is_some() {}
+        self.bloom_filter.unset(key);
+
+        Ok(())
+    }
+
     pub async fn load<F>(&mut self, key: &K, weight: usize, mut hot_cache_op: F) -> Option<V>
     where
         F: FnMut(&mut DefaultCacher<K, V, C, D>, &K) -> Option<V>,
@@ -66,6 +132,7 @@ where
         let mut instant = std::time::Instant::now();
         self.requested += 1;
         if !self.bloom_filter.check(key) {
+            self.bloom_rejected += 1;
             trace!(key = ?key, elapsed = ?instant.elapsed(), "Not exist in bloom filter");
             return None;
         }
@@ -79,12 +146,12 @@ where
         trace!(?key, elapsed = ?check_hot_cache_elapsed, "Check hot cache");
         instant += check_hot_cache_elapsed;
         if maybe.is_some() {
-            self.in_hotcache += 1;
-            let hit_ratio = self.in_hotcache as f64 / self.requested as f64;
+            self.hot_cache_hits += 1;
+            let hit_ratio = self.hot_cache_hits as f64 / self.requested as f64;
             trace!(
                 ?key,
                 requested = %self.requested,
-                in_hotcache = %self.in_hotcache,
+                in_hotcache = %self.hot_cache_hits,
                 "Got from hot cache, hit_ratio: {:.2}", hit_ratio
             );
             return maybe;
@@ -111,50 +178,58 @@ where
         weight: usize,
         instant: std::time::Instant,
     ) -> Option<V> {
-        self.disk_cache.load(key).await.ok()?.map(|v| {
-            trace!(key = ?key, elapsed = ?instant.elapsed(), "Load from disk");
-            self.hot_cache
-                .insert_with_weight(CacheEntry::new(key.clone(), v.clone()), weight);
-            v
-        })
+        match self.disk_cache.load(key).await.ok()? {
+            Some(v) => {
+                self.disk_hits += 1;
+                trace!(key = ?key, elapsed = ?instant.elapsed(), "Load from disk");
+                self.hot_cache
+                    .insert_with_weight(CacheEntry::new(key.clone(), v.clone()), weight);
+                Some(v)
+            }
+            None => {
+                self.disk_misses += 1;
+                trace!(key = ?key, elapsed = ?instant.elapsed(), "Bloom filter false positive");
+                None
+            }
+        }
     }
 
     pub fn disk_cacher(&self) -> &D {
         &self.disk_cache
     }
 
-    // TODO: use mmap to sync data
-    pub async fn init_bloom_filter(&mut self) -> anyhow::Result<()> {
-        let instant = std::time::Instant::now();
-
-        let disk_dir = self.disk_cache.directory();
-        let mut dirs = vec![];
-        let mut disk_dir = tokio::fs::read_dir(disk_dir).await.unwrap();
-        while let Ok(Some(dir_entry)) = disk_dir.next_entry().await {
-            if let Ok(file_type) = dir_entry.file_type().await {
-                if file_type.is_dir() {
-                    dirs.push(dir_entry.path())
-                }
-            }
+    /// Snapshot of how this cacher's lookups have resolved so far. See
+    /// [`CacheStats`].
+    pub fn stats(&self) -> CacheStats {
+        CacheStats {
+            requested: self.requested,
+            bloom_rejected: self.bloom_rejected,
+            hot_cache_hits: self.hot_cache_hits,
+            disk_hits: self.disk_hits,
+            disk_misses: self.disk_misses,
         }
+    }
 
-        let keys: Vec<_> = tokio::task::spawn_blocking(move || {
-            dirs.iter()
-                .filter_map(|dir| std::fs::read_dir(dir).ok())
-                .flatten()
-                .map(Result::unwrap)
-                .filter(|dir| dir.file_type().unwrap().is_file())
-                .filter_map(|entry| entry.file_name().into_string().ok())
-                .collect()
-        })
-        .await?;
+    /// Zero every counter backing [`stats`](Self::stats), e.g. to measure a
+    /// fresh window instead of a lifetime total.
+    pub fn reset_stats(&mut self) {
+        self.requested = 0;
+        self.bloom_rejected = 0;
+        self.hot_cache_hits = 0;
+        self.disk_hits = 0;
+        self.disk_misses = 0;
+    }
 
-        keys.into_iter()
-            .filter_map(|k| k.try_into().ok())
-            .for_each(|key| self.bloom_filter.set(&key));
+    pub async fn init_bloom_filter(&mut self) -> anyhow::Result<()> {
+        let instant = std::time::Instant::now();
+
+        let keys = self.disk_cache.keys().await;
+        let count = keys.len();
+        keys.into_iter().for_each(|key| self.bloom_filter.set(&key));
 
         trace!(
             elapsed = ?instant.elapsed(),
+            count = %count,
             "Inited bloom filter",
         );
 
@@ -162,6 +237,55 @@ where
     }
 }
 
+/// A snapshot of how a [`DefaultCacher`]'s lookups have resolved, returned
+/// by [`DefaultCacher::stats`].
+///
+/// Counters are cumulative since construction, or since the last
+/// [`reset_stats`](DefaultCacher::reset_stats), so callers can sample a
+/// window instead of a lifetime total.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct CacheStats {
+    /// Every call to [`DefaultCacher::load`].
+    pub requested: u128,
+    /// Requests the Bloom filter ruled out before touching the hot cache or disk.
+    pub bloom_rejected: u128,
+    /// Requests served straight from the hot cache.
+    pub hot_cache_hits: u128,
+    /// Requests that missed the hot cache but were found on disk.
+    pub disk_hits: u128,
+    /// Requests the Bloom filter let through that disk didn't actually have:
+    /// a false positive, or an entry that went stale between the filter
+    /// check and the disk lookup.
+    pub disk_misses: u128,
+}
+
+impl CacheStats {
+    /// Fraction of all requests served from either the hot cache or disk.
+    pub fn hit_ratio(&self) -> f64 {
+        Self::ratio(self.hot_cache_hits + self.disk_hits, self.requested)
+    }
+
+    /// Fraction of all requests served from the hot cache alone.
+    pub fn hot_cache_hit_ratio(&self) -> f64 {
+        Self::ratio(self.hot_cache_hits, self.requested)
+    }
+
+    /// Fraction of disk lookups (Bloom-filter passes that also missed the hot
+    /// cache) that didn't actually find the key on disk. Hot-cache hits are
+    /// not counted in the denominator.
+    pub fn bloom_false_positive_rate(&self) -> f64 {
+        Self::ratio(self.disk_misses, self.disk_hits + self.disk_misses)
+    }
+
+    fn ratio(part: u128, whole: u128) -> f64 {
+        if whole == 0 {
+            0.0
+        } else {
+            part as f64 / whole as f64
+        }
+    }
+}
+
 #[derive(Clone, Debug)]
 pub struct CacheEntry<K, V> {
     key: K,
@@ -173,3 +297,217 @@ impl<K: Clone, V: Clone> CacheEntry<K, V> {
         Self { key, value }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::PieceIndex;
+    use std::collections::HashMap;
+    use std::path::Path;
+
+    /// Minimal in-memory [`Cache`] test double: no eviction, just a `Vec`.
+    struct VecCache<T>(Vec<T>);
+
+    impl<T> Default for VecCache<T> {
+        fn default() -> Self {
+            Self(Vec::new())
+        }
+    }
+
+    impl<T> Cache<T> for VecCache<T> {
+        fn insert_with_weight(&mut self, item: T, _weight: usize) {
+            self.0.push(item);
+        }
+
+        fn find<F>(&mut self, mut predicate: F) -> Option<&T>
+        where
+            F: FnMut(&T) -> bool,
+        {
+            self.0.iter().find(|item| predicate(item))
+        }
+
+        fn lookup<F>(&mut self, predicate: F) -> Option<&T>
+        where
+            F: FnMut(&T) -> bool,
+        {
+            self.find(predicate)
+        }
+
+        fn remove<F>(&mut self, mut predicate: F) -> Option<T>
+        where
+            F: FnMut(&T) -> bool,
+        {
+            let index = self.0.iter().position(|item| predicate(item))?;
+            Some(self.0.remove(index))
+        }
+    }
+
+    /// Minimal in-memory [`DiskCache`] test double backed by a `HashMap`.
+    #[derive(Default)]
+    struct MapDiskCache(HashMap<PieceIndex, u8>);
+
+    impl DiskCache<PieceIndex, u8> for MapDiskCache {
+        type Error = std::convert::Infallible;
+
+        async fn load(&self, key: &PieceIndex) -> Result<Option<u8>, Self::Error> {
+            Ok(self.0.get(key).copied())
+        }
+
+        async fn store(
+            &mut self,
+            key: &PieceIndex,
+            value: u8,
+        ) -> Result<Option<PieceIndex>, Self::Error> {
+            self.0.insert(*key, value);
+            Ok(None)
+        }
+
+        async fn remove(&mut self, key: &PieceIndex) -> Result<(), Self::Error> {
+            self.0.remove(key);
+            Ok(())
+        }
+
+        async fn exist(&self, key: &PieceIndex) -> bool {
+            self.0.contains_key(key)
+        }
+
+        fn exist_sync(&self, key: &PieceIndex) -> bool {
+            self.0.contains_key(key)
+        }
+
+        fn directory(&self) -> &Path {
+            Path::new(".")
+        }
+
+        async fn keys(&self) -> Vec<PieceIndex> {
+            self.0.keys().copied().collect()
+        }
+    }
+
+    #[tokio::test]
+    async fn remove_clears_a_key_that_was_stored_twice() {
+        let mut cacher: DefaultCacher<PieceIndex, u8, VecCache<CacheEntry<PieceIndex, u8>>, MapDiskCache> =
+            DefaultCacher::new(MapDiskCache::default(), 10, 0.01);
+
+        let key = PieceIndex::ZERO;
+        cacher.store(key, 1, 0).await.unwrap();
+        // Re-store the same key: before the fix this bumped the bloom
+        // filter's counters a second time, so the single `unset` below would
+        // leave them one too high and `remove` would never actually clear it.
+        cacher.store(key, 2, 0).await.unwrap();
+        cacher.remove(&key).await.unwrap();
+
+        assert!(!cacher.bloom_filter.check(&key));
+    }
+
+    fn temp_path(name: &str) -> std::path::PathBuf {
+        let nanos = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .expect("system clock is after the epoch")
+            .as_nanos();
+        std::env::temp_dir().join(format!("cache-syncer-cacher-bloom-{name}-{nanos}"))
+    }
+
+    #[tokio::test]
+    async fn new_and_load_bloom_restores_the_sidecar_instead_of_rescanning() {
+        let path = temp_path("round-trip");
+        let key = PieceIndex::ZERO;
+
+        {
+            let mut cacher: DefaultCacher<PieceIndex, u8, VecCache<CacheEntry<PieceIndex, u8>>, MapDiskCache> =
+                DefaultCacher::new_and_load_bloom(MapDiskCache::default(), 10, 0.01, path.clone())
+                    .await
+                    .unwrap();
+            cacher.store(key, 1, 0).await.unwrap();
+            cacher.flush_bloom_filter().unwrap();
+        }
+
+        // A fresh, empty disk cache stands in for a different process that
+        // hasn't scanned disk yet: the persisted sidecar must still report
+        // `key` present, proving it was loaded as-is rather than rebuilt.
+        let reopened: DefaultCacher<PieceIndex, u8, VecCache<CacheEntry<PieceIndex, u8>>, MapDiskCache> =
+            DefaultCacher::new_and_load_bloom(MapDiskCache::default(), 10, 0.01, path.clone())
+                .await
+                .unwrap();
+        assert!(reopened.bloom_filter.check(&key));
+
+        std::fs::remove_file(&path).ok();
+    }
+
+    #[tokio::test]
+    async fn new_and_load_bloom_falls_back_to_a_scan_on_parameter_mismatch() {
+        let path = temp_path("mismatch");
+        let key = PieceIndex::ZERO;
+
+        {
+            let mut cacher: DefaultCacher<PieceIndex, u8, VecCache<CacheEntry<PieceIndex, u8>>, MapDiskCache> =
+                DefaultCacher::new_and_load_bloom(MapDiskCache::default(), 10, 0.01, path.clone())
+                    .await
+                    .unwrap();
+            cacher.store(key, 1, 0).await.unwrap();
+            cacher.flush_bloom_filter().unwrap();
+        }
+
+        // Requesting a different `items_count` makes the sidecar untrustworthy,
+        // so the cacher must fall back to scanning the disk it was given here.
+        // The disk holds a key the sidecar never saw, so only a scan finds it.
+        let disk_key = PieceIndex::ONE;
+        let mut disk = MapDiskCache::default();
+        disk.0.insert(disk_key, 9);
+        let reopened: DefaultCacher<PieceIndex, u8, VecCache<CacheEntry<PieceIndex, u8>>, MapDiskCache> =
+            DefaultCacher::new_and_load_bloom(disk, 20, 0.01, path.clone())
+                .await
+                .unwrap();
+        assert!(reopened.bloom_filter.check(&disk_key));
+
+        std::fs::remove_file(&path).ok();
+    }
+
+    type TestCacher = DefaultCacher<PieceIndex, u8, VecCache<CacheEntry<PieceIndex, u8>>, MapDiskCache>;
+
+    fn miss_hot_cache(_: &mut TestCacher, _: &PieceIndex) -> Option<u8> {
+        None
+    }
+
+    #[tokio::test]
+    async fn stats_reports_bloom_rejections_hot_disk_hits_and_misses() {
+        let mut cacher: TestCacher = DefaultCacher::new(MapDiskCache::default(), 100, 0.01);
+
+        let hot_key = PieceIndex::ZERO;
+        let disk_key = PieceIndex::ONE;
+        let stale_key = PieceIndex::from(2);
+        let absent_key = PieceIndex::from(3);
+
+        cacher.store(hot_key, 1, 0).await.unwrap();
+        cacher.store(disk_key, 2, 0).await.unwrap();
+        cacher.store(stale_key, 3, 0).await.unwrap();
+        // Simulate the entry going stale on disk behind the bloom filter's
+        // back (e.g. an out-of-band eviction), rather than via `remove`.
+        cacher.disk_cache.0.remove(&stale_key);
+
+        // Rejected by the bloom filter outright: never stored.
+        assert_eq!(cacher.load(&absent_key, 0, miss_hot_cache).await, None);
+        // Served straight from the hot cache.
+        assert_eq!(
+            cacher.load(&hot_key, 0, |c, k| c.load_from_hot_cache(k)).await,
+            Some(1)
+        );
+        // Misses the hot cache, hits disk.
+        assert_eq!(cacher.load(&disk_key, 0, miss_hot_cache).await, Some(2));
+        // Bloom filter says present, but disk has nothing: a stale entry.
+        assert_eq!(cacher.load(&stale_key, 0, miss_hot_cache).await, None);
+
+        let stats = cacher.stats();
+        assert_eq!(stats.requested, 4);
+        assert_eq!(stats.bloom_rejected, 1);
+        assert_eq!(stats.hot_cache_hits, 1);
+        assert_eq!(stats.disk_hits, 1);
+        assert_eq!(stats.disk_misses, 1);
+        assert_eq!(stats.hit_ratio(), 0.5);
+        assert_eq!(stats.hot_cache_hit_ratio(), 0.25);
+        assert_eq!(stats.bloom_false_positive_rate(), 0.5);
+
+        cacher.reset_stats();
+        assert_eq!(cacher.stats(), CacheStats::default());
+    }
+}
@@ -0,0 +1,41 @@
+use std::future::Future;
+use std::path::Path;
+
+/// A disk-backed store keyed by `K`, holding values of type `V`.
+///
+/// Implementations sit behind [`DefaultCacher`](crate::DefaultCacher) as the
+/// cold tier: every lookup that survives the Bloom filter and misses the hot
+/// cache falls through to here.
+pub trait DiskCache<K, V> {
+    type Error: std::error::Error + Send + Sync + 'static;
+
+    fn load(&self, key: &K) -> impl Future<Output = Result<Option<V>, Self::Error>> + Send;
+
+    /// Store `value` under `key`.
+    ///
+    /// Returns the key of an entry that was evicted from disk as a side
+    /// effect of this store (e.g. a slot collision in a fixed-capacity
+    /// backend), if any, so the caller can reconcile auxiliary state such as
+    /// a [`BloomFilter`](crate::BloomFilter) for it.
+    fn store(
+        &mut self,
+        key: &K,
+        value: V,
+    ) -> impl Future<Output = Result<Option<K>, Self::Error>> + Send;
+
+    /// Remove `key` from disk, if present.
+    fn remove(&mut self, key: &K) -> impl Future<Output = Result<(), Self::Error>> + Send;
+
+    fn exist(&self, key: &K) -> impl Future<Output = bool> + Send;
+
+    fn exist_sync(&self, key: &K) -> bool;
+
+    fn directory(&self) -> &Path;
+
+    /// Enumerate every key currently stored on disk.
+    ///
+    /// Used to warm up a [`BloomFilter`](crate::BloomFilter) on startup
+    /// without the caller needing to know how this backend lays out its
+    /// data on disk.
+    fn keys(&self) -> impl Future<Output = Vec<K>> + Send;
+}
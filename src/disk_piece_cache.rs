@@ -0,0 +1,600 @@
+use std::{
+    fs, io, mem,
+    path::{Path, PathBuf},
+    str::FromStr,
+    sync::Arc,
+};
+
+#[repr(transparent)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct PieceIndex(u64);
+
+impl From<u64> for PieceIndex {
+    #[inline]
+    fn from(original: u64) -> Self {
+        Self(original)
+    }
+}
+
+impl From<PieceIndex> for u64 {
+    #[inline]
+    fn from(original: PieceIndex) -> Self {
+        original.0
+    }
+}
+
+impl TryFrom<String> for PieceIndex {
+    type Error = <u64 as FromStr>::Err;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        value.parse().map(|p| PieceIndex(p))
+    }
+}
+
+impl PieceIndex {
+    /// Size in bytes.
+    pub const SIZE: usize = mem::size_of::<u64>();
+    /// Piece index 0.
+    pub const ZERO: PieceIndex = PieceIndex(0);
+    /// Piece index 1.
+    pub const ONE: PieceIndex = PieceIndex(1);
+
+    /// Create piece index from bytes.
+    #[inline]
+    pub const fn from_bytes(bytes: [u8; Self::SIZE]) -> Self {
+        Self(u64::from_le_bytes(bytes))
+    }
+
+    /// Convert piece index to bytes.
+    #[inline]
+    pub const fn to_bytes(self) -> [u8; Self::SIZE] {
+        self.0.to_le_bytes()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Ord, PartialOrd, Hash)]
+pub struct Piece(pub Vec<u8>);
+
+impl Piece {
+    /// Size of a piece (in bytes).
+    pub const SIZE: usize = 1048672;
+}
+
+impl Default for Piece {
+    #[inline]
+    fn default() -> Self {
+        Self(vec![0u8; Piece::SIZE])
+    }
+}
+
+/// Disk piece cache open error
+#[derive(Debug, thiserror::Error)]
+pub enum DiskPieceCacheError {
+    /// I/O error occurred
+    #[error("I/O error: {0}")]
+    Io(#[from] io::Error),
+    /// Cache file header is missing, corrupt, or was built with different
+    /// parameters than the ones requested on open
+    #[error("invalid or mismatched cache file header")]
+    InvalidHeader,
+    /// Stored piece has an unrecognized compression tag
+    #[error("unknown compression tag {0}")]
+    UnknownCompressionTag(u8),
+    /// Stored piece failed its checksum, its frame was too short to contain
+    /// one (e.g. a truncated file), or its payload did not decode to exactly
+    /// `Piece::SIZE` bytes
+    #[error("checksum mismatch for piece {0:?}")]
+    ChecksumMismatch(PieceIndex),
+}
+
+/// One-byte tag prefixed to every stored piece, mirroring `DataBlock` in
+/// Garage: `Plain` bytes are the raw piece, `Compressed` bytes are zstd and
+/// get inflated back to `Piece::SIZE` on read.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+enum CompressionTag {
+    Plain = 0,
+    Zstd = 1,
+}
+
+impl CompressionTag {
+    fn from_byte(byte: u8) -> Result<Self, DiskPieceCacheError> {
+        match byte {
+            0 => Ok(Self::Plain),
+            1 => Ok(Self::Zstd),
+            other => Err(DiskPieceCacheError::UnknownCompressionTag(other)),
+        }
+    }
+}
+
+const TAG_SIZE: usize = 1;
+const LEN_PREFIX_SIZE: usize = mem::size_of::<u32>();
+const CHECKSUM_SIZE: usize = mem::size_of::<u32>();
+const FRAME_HEADER_SIZE: usize = TAG_SIZE + LEN_PREFIX_SIZE + CHECKSUM_SIZE;
+
+/// Result of a full [`DiskPieceCache::scrub`] pass.
+#[derive(Debug, Clone, Default)]
+pub struct ScrubReport {
+    /// Pieces that read back and passed their checksum.
+    pub ok: usize,
+    /// Pieces whose stored checksum didn't match their bytes.
+    pub corrupt: usize,
+    /// Pieces that were listed on disk but could not be read back at all.
+    pub missing: usize,
+    /// Indices of every corrupt piece, for targeted repair.
+    pub corrupt_pieces: Vec<PieceIndex>,
+    /// Indices of every missing piece, for targeted repair.
+    pub missing_pieces: Vec<PieceIndex>,
+}
+
+impl ScrubReport {
+    /// Every piece this scrub flagged as unusable, corrupt or missing.
+    pub fn unhealthy(&self) -> impl Iterator<Item = PieceIndex> + '_ {
+        self.corrupt_pieces
+            .iter()
+            .chain(self.missing_pieces.iter())
+            .copied()
+    }
+}
+
+#[derive(Debug)]
+struct Inner {
+    piece_dir: PathBuf,
+    /// zstd level to compress with, or `None` to always store pieces plain.
+    compression_level: Option<i32>,
+}
+
+const M: u64 = 1024;
+
+/// Piece cache stored on one disk
+#[derive(Debug, Clone)]
+pub struct DiskPieceCache {
+    inner: Arc<Inner>,
+}
+
+impl DiskPieceCache {
+    pub fn open(directory: &Path) -> Result<Self, DiskPieceCacheError> {
+        Self::open_internal(directory, None)
+    }
+
+    /// Open the cache with zstd compression enabled at `compression_level`.
+    /// A piece is only stored compressed when doing so is actually smaller
+    /// than storing it plain.
+    pub fn open_with_compression(
+        directory: &Path,
+        compression_level: i32,
+    ) -> Result<Self, DiskPieceCacheError> {
+        Self::open_internal(directory, Some(compression_level))
+    }
+
+    fn open_internal(
+        directory: &Path,
+        compression_level: Option<i32>,
+    ) -> Result<Self, DiskPieceCacheError> {
+        Ok(Self {
+            inner: Arc::new(Inner {
+                piece_dir: directory.to_path_buf(),
+                compression_level,
+            }),
+        })
+    }
+
+    pub async fn remove_piece(&self, piece_index: PieceIndex) {
+        let (filename, _) = self.piece_filenames(piece_index);
+        if let Err(error) = tokio::fs::remove_file(filename).await {
+            if error.kind() != io::ErrorKind::NotFound {
+                panic!("failed to remove piece {piece_index:?}: {error}");
+            }
+        }
+    }
+
+    pub async fn write_piece(
+        &self,
+        piece_index: PieceIndex,
+        piece: Piece,
+    ) -> Result<(), DiskPieceCacheError> {
+        let (filename, tmp_filename) = self.piece_filenames(piece_index);
+        let raw = piece.0;
+
+        let (tag, payload) = match self.inner.compression_level {
+            Some(level) => {
+                let compressed =
+                    zstd::stream::encode_all(raw.as_slice(), level).map_err(DiskPieceCacheError::Io)?;
+                if compressed.len() < raw.len() {
+                    (CompressionTag::Zstd, compressed)
+                } else {
+                    (CompressionTag::Plain, raw)
+                }
+            }
+            None => (CompressionTag::Plain, raw),
+        };
+
+        let checksum = xxhash_rust::xxh32::xxh32(&payload, 0);
+
+        let mut file_bytes = Vec::with_capacity(FRAME_HEADER_SIZE + payload.len());
+        file_bytes.push(tag as u8);
+        file_bytes.extend_from_slice(&(payload.len() as u32).to_le_bytes());
+        file_bytes.extend_from_slice(&checksum.to_le_bytes());
+        file_bytes.extend_from_slice(&payload);
+
+        if let Some(basedir) = filename.parent() {
+            fs::create_dir_all(basedir).map_err(DiskPieceCacheError::Io)?;
+        }
+        tokio::fs::write(&tmp_filename, file_bytes)
+            .await
+            .map_err(DiskPieceCacheError::Io)?;
+        tokio::fs::rename(tmp_filename, filename)
+            .await
+            .map_err(DiskPieceCacheError::Io)?;
+        Ok(())
+    }
+
+    pub async fn has_piece(&self, piece_index: PieceIndex) -> bool {
+        let (filename, _) = self.piece_filenames(piece_index);
+        tokio::fs::try_exists(filename).await.unwrap_or(false)
+    }
+
+    pub fn has_piece_sync(&self, piece_index: PieceIndex) -> bool {
+        let (filename, _) = self.piece_filenames(piece_index);
+        std::fs::try_exists(filename).unwrap_or(false)
+    }
+
+    /// Read piece from cache
+    pub async fn read_piece(
+        &self,
+        piece_index: PieceIndex,
+    ) -> Result<Option<Piece>, DiskPieceCacheError> {
+        if !self.has_piece(piece_index).await {
+            return Ok(None);
+        }
+        let (filename, _) = self.piece_filenames(piece_index);
+        let bs = fs::read(&filename).map_err(DiskPieceCacheError::Io)?;
+
+        if bs.len() < FRAME_HEADER_SIZE {
+            return Err(DiskPieceCacheError::ChecksumMismatch(piece_index));
+        }
+
+        let tag = CompressionTag::from_byte(bs[0])?;
+        let len = u32::from_le_bytes(
+            bs[TAG_SIZE..TAG_SIZE + LEN_PREFIX_SIZE]
+                .try_into()
+                .expect("slice is LEN_PREFIX_SIZE bytes; qed"),
+        ) as usize;
+        let stored_checksum = u32::from_le_bytes(
+            bs[TAG_SIZE + LEN_PREFIX_SIZE..FRAME_HEADER_SIZE]
+                .try_into()
+                .expect("slice is CHECKSUM_SIZE bytes; qed"),
+        );
+        let Some(payload) = bs.get(FRAME_HEADER_SIZE..FRAME_HEADER_SIZE + len) else {
+            return Err(DiskPieceCacheError::ChecksumMismatch(piece_index));
+        };
+        if xxhash_rust::xxh32::xxh32(payload, 0) != stored_checksum {
+            return Err(DiskPieceCacheError::ChecksumMismatch(piece_index));
+        }
+
+        let mut piece = Piece::default();
+        match tag {
+            CompressionTag::Plain => {
+                if payload.len() != Piece::SIZE {
+                    return Err(DiskPieceCacheError::ChecksumMismatch(piece_index));
+                }
+                piece.0.copy_from_slice(payload);
+            }
+            CompressionTag::Zstd => {
+                let decompressed = zstd::stream::decode_all(payload).map_err(DiskPieceCacheError::Io)?;
+                if decompressed.len() != Piece::SIZE {
+                    return Err(DiskPieceCacheError::ChecksumMismatch(piece_index));
+                }
+                piece.0.copy_from_slice(&decompressed);
+            }
+        }
+        Ok(Some(piece))
+    }
+
+    /// Walk every piece stored on disk, verifying its checksum.
+    ///
+    /// Unlike [`read_piece`](Self::read_piece), which only ever looks at one
+    /// piece, this is meant to be run as a periodic or on-demand repair pass
+    /// over the whole cache.
+    pub async fn scrub(&self) -> ScrubReport {
+        let mut report = ScrubReport::default();
+        for piece_index in self.stored_indices().await {
+            match self.read_piece(piece_index).await {
+                Ok(Some(_)) => report.ok += 1,
+                Ok(None) => {
+                    report.missing += 1;
+                    report.missing_pieces.push(piece_index);
+                }
+                Err(DiskPieceCacheError::ChecksumMismatch(_)) => {
+                    report.corrupt += 1;
+                    report.corrupt_pieces.push(piece_index);
+                }
+                Err(_) => {
+                    report.missing += 1;
+                    report.missing_pieces.push(piece_index);
+                }
+            }
+        }
+        report
+    }
+
+    /// Remove every piece a [`ScrubReport`] flagged as corrupt or missing, so
+    /// the Bloom filter and hot cache can be reconciled against what is
+    /// actually left on disk.
+    pub async fn repair(&self, report: &ScrubReport) {
+        for piece_index in report.unhealthy() {
+            self.remove_piece(piece_index).await;
+        }
+    }
+
+    async fn stored_indices(&self) -> Vec<PieceIndex> {
+        let piece_dir = self.inner.piece_dir.clone();
+        let mut dirs = vec![];
+        let Ok(mut disk_dir) = tokio::fs::read_dir(&piece_dir).await else {
+            return vec![];
+        };
+        while let Ok(Some(dir_entry)) = disk_dir.next_entry().await {
+            if let Ok(file_type) = dir_entry.file_type().await {
+                if file_type.is_dir() {
+                    dirs.push(dir_entry.path())
+                }
+            }
+        }
+
+        tokio::task::spawn_blocking(move || {
+            dirs.iter()
+                .filter_map(|dir| std::fs::read_dir(dir).ok())
+                .flatten()
+                // A racing removal between `read_dir` and `file_type()` (or
+                // a permission hiccup) just drops the entry instead of
+                // panicking: `scrub`/`repair` exist to tolerate a flaky
+                // disk, not crash on one.
+                .filter_map(Result::ok)
+                .filter(|entry| entry.file_type().map(|ft| ft.is_file()).unwrap_or(false))
+                .filter_map(|entry| entry.file_name().into_string().ok())
+                .filter_map(|name| name.try_into().ok())
+                .collect()
+        })
+        .await
+        .unwrap_or_default()
+    }
+
+    fn piece_filenames(&self, piece_index: PieceIndex) -> (PathBuf, PathBuf) {
+        let piece_index = u64::from(piece_index);
+        let sub_dir = format!("{}", piece_index % M);
+        let filename = self
+            .inner
+            .piece_dir
+            .join(&sub_dir)
+            .join(u64::from(piece_index).to_string());
+
+        let tmp_filename = self
+            .inner
+            .piece_dir
+            .join(sub_dir)
+            .join(format!("{}.tmp", piece_index));
+        (filename, tmp_filename)
+    }
+}
+
+impl crate::DiskCache<PieceIndex, Piece> for DiskPieceCache {
+    type Error = DiskPieceCacheError;
+
+    fn load(
+        &self,
+        key: &PieceIndex,
+    ) -> impl std::future::Future<Output = Result<Option<Piece>, Self::Error>> + Send {
+        self.read_piece(*key)
+    }
+
+    fn store(
+        &mut self,
+        key: &PieceIndex,
+        value: Piece,
+    ) -> impl std::future::Future<Output = Result<Option<PieceIndex>, Self::Error>> + Send {
+        let piece_index = *key;
+        async move {
+            self.write_piece(piece_index, value).await?;
+            // One file per piece index: a store can never evict a different key.
+            Ok(None)
+        }
+    }
+
+    fn remove(
+        &mut self,
+        key: &PieceIndex,
+    ) -> impl std::future::Future<Output = Result<(), Self::Error>> + Send {
+        let piece_index = *key;
+        async move {
+            self.remove_piece(piece_index).await;
+            Ok(())
+        }
+    }
+
+    fn exist(&self, key: &PieceIndex) -> impl std::future::Future<Output = bool> + Send {
+        self.has_piece(*key)
+    }
+
+    fn exist_sync(&self, key: &PieceIndex) -> bool {
+        self.has_piece_sync(*key)
+    }
+
+    fn directory(&self) -> &std::path::Path {
+        self.inner.piece_dir.as_path()
+    }
+
+    fn keys(&self) -> impl std::future::Future<Output = Vec<PieceIndex>> + Send {
+        self.stored_indices()
+    }
+}
+
+pub(crate) struct FakeDiskCache;
+
+impl crate::DiskCache<PieceIndex, Piece> for FakeDiskCache {
+    type Error = DiskPieceCacheError;
+
+    async fn load(&self, _key: &PieceIndex) -> Result<Option<Piece>, Self::Error> {
+        Ok(None)
+    }
+
+    async fn store(
+        &mut self,
+        _key: &PieceIndex,
+        _value: Piece,
+    ) -> Result<Option<PieceIndex>, Self::Error> {
+        Ok(None)
+    }
+
+    async fn remove(&mut self, _key: &PieceIndex) -> Result<(), Self::Error> {
+        Ok(())
+    }
+
+    async fn exist(&self, _key: &PieceIndex) -> bool {
+        false
+    }
+
+    fn exist_sync(&self, _key: &PieceIndex) -> bool {
+        false
+    }
+
+    fn directory(&self) -> &Path {
+        Path::new("./pieces-cache/0")
+    }
+
+    async fn keys(&self) -> Vec<PieceIndex> {
+        vec![]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::{SystemTime, UNIX_EPOCH};
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let nanos = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .expect("system clock is after the epoch")
+            .as_nanos();
+        std::env::temp_dir().join(format!("cache-syncer-disk-{name}-{nanos}"))
+    }
+
+    /// A pseudo-random, non-repeating fill that zstd can't meaningfully
+    /// shrink, unlike an all-zero [`Piece::default`].
+    fn incompressible_piece() -> Piece {
+        let mut state: u64 = 0x2545_f491_4f6c_dd1d;
+        let bytes = (0..Piece::SIZE)
+            .map(|_| {
+                state ^= state << 13;
+                state ^= state >> 7;
+                state ^= state << 17;
+                (state & 0xff) as u8
+            })
+            .collect();
+        Piece(bytes)
+    }
+
+    #[tokio::test]
+    async fn stores_highly_compressible_pieces_compressed_and_round_trips() {
+        let dir = temp_dir("zstd-compressible");
+        let cache = DiskPieceCache::open_with_compression(&dir, 3).unwrap();
+        let index = PieceIndex::ZERO;
+
+        cache.write_piece(index, Piece::default()).await.unwrap();
+
+        let (filename, _) = cache.piece_filenames(index);
+        let on_disk_len = std::fs::metadata(&filename).unwrap().len() as usize;
+        assert!(
+            on_disk_len < FRAME_HEADER_SIZE + Piece::SIZE,
+            "an all-zero piece should have compressed smaller than storing it plain"
+        );
+
+        assert_eq!(cache.read_piece(index).await.unwrap(), Some(Piece::default()));
+
+        std::fs::remove_dir_all(&dir).ok();
+    }
+
+    #[tokio::test]
+    async fn falls_back_to_plain_when_compression_does_not_help() {
+        let dir = temp_dir("zstd-incompressible");
+        let cache = DiskPieceCache::open_with_compression(&dir, 3).unwrap();
+        let index = PieceIndex::ZERO;
+        let piece = incompressible_piece();
+
+        cache.write_piece(index, piece.clone()).await.unwrap();
+
+        let (filename, _) = cache.piece_filenames(index);
+        let on_disk_len = std::fs::metadata(&filename).unwrap().len() as usize;
+        assert_eq!(
+            on_disk_len,
+            FRAME_HEADER_SIZE + Piece::SIZE,
+            "incompressible data should have been stored plain, not inflated further"
+        );
+
+        assert_eq!(cache.read_piece(index).await.unwrap(), Some(piece));
+
+        std::fs::remove_dir_all(&dir).ok();
+    }
+
+    #[tokio::test]
+    async fn read_piece_rejects_a_payload_that_decodes_to_the_wrong_size() {
+        let dir = temp_dir("short-payload");
+        let cache = DiskPieceCache::open(&dir).unwrap();
+        let index = PieceIndex::ZERO;
+
+        // A frame whose length prefix and checksum are internally
+        // consistent, but whose payload is shorter than `Piece::SIZE` — as
+        // if the file were garbled past the frame header.
+        let payload = vec![9u8; 10];
+        let checksum = xxhash_rust::xxh32::xxh32(&payload, 0);
+        let mut bytes = vec![CompressionTag::Plain as u8];
+        bytes.extend_from_slice(&(payload.len() as u32).to_le_bytes());
+        bytes.extend_from_slice(&checksum.to_le_bytes());
+        bytes.extend_from_slice(&payload);
+
+        let (filename, _) = cache.piece_filenames(index);
+        std::fs::create_dir_all(filename.parent().unwrap()).unwrap();
+        std::fs::write(&filename, bytes).unwrap();
+
+        let result = cache.read_piece(index).await;
+        assert!(matches!(
+            result,
+            Err(DiskPieceCacheError::ChecksumMismatch(_))
+        ));
+
+        std::fs::remove_dir_all(&dir).ok();
+    }
+
+    #[tokio::test]
+    async fn scrub_flags_corrupted_pieces_and_repair_removes_only_those() {
+        let dir = temp_dir("scrub");
+        let cache = DiskPieceCache::open(&dir).unwrap();
+        let good = PieceIndex::ZERO;
+        let bad = PieceIndex::ONE;
+
+        cache.write_piece(good, Piece::default()).await.unwrap();
+        cache
+            .write_piece(bad, Piece(vec![7u8; Piece::SIZE]))
+            .await
+            .unwrap();
+
+        // Flip a byte in `bad`'s payload so its stored checksum no longer matches.
+        let (filename, _) = cache.piece_filenames(bad);
+        let mut bytes = std::fs::read(&filename).unwrap();
+        let last = bytes.len() - 1;
+        bytes[last] ^= 0xff;
+        std::fs::write(&filename, &bytes).unwrap();
+
+        let report = cache.scrub().await;
+        assert_eq!(report.ok, 1);
+        assert_eq!(report.corrupt, 1);
+        assert_eq!(report.missing, 0);
+        assert_eq!(report.corrupt_pieces, vec![bad]);
+
+        cache.repair(&report).await;
+        assert!(!cache.has_piece(bad).await);
+        assert!(cache.has_piece(good).await);
+
+        std::fs::remove_dir_all(&dir).ok();
+    }
+}
@@ -0,0 +1,17 @@
+//! A two-tier cache: a bounded, weighted in-memory hot cache in front of a
+//! disk-backed store, fronted by a Bloom filter so disk lookups are only
+//! attempted for keys that might actually be present.
+
+mod bloom_filter;
+mod cache;
+mod default_cacher;
+mod disk_cache;
+mod disk_piece_cache;
+mod mmap_piece_cache;
+
+pub use bloom_filter::BloomFilter;
+pub use cache::Cache;
+pub use default_cacher::{CacheEntry, CacheStats, DefaultCacher};
+pub use disk_cache::DiskCache;
+pub use disk_piece_cache::{DiskPieceCache, DiskPieceCacheError, Piece, PieceIndex, ScrubReport};
+pub use mmap_piece_cache::MmapPieceCache;
@@ -0,0 +1,370 @@
+use crate::disk_piece_cache::{DiskPieceCacheError, Piece, PieceIndex};
+use memmap2::MmapMut;
+use std::fs::{File, OpenOptions};
+use std::io::{Read, Seek, SeekFrom};
+use std::mem;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+
+const MAGIC: [u8; 8] = *b"CSPCMMP1";
+const VERSION: u32 = 1;
+
+const MAGIC_SIZE: usize = MAGIC.len();
+const VERSION_SIZE: usize = mem::size_of::<u32>();
+const MAX_PIECES_SIZE: usize = mem::size_of::<u64>();
+const ELEMENT_COUNT_SIZE: usize = mem::size_of::<u64>();
+
+const MAGIC_OFFSET: usize = 0;
+const VERSION_OFFSET: usize = MAGIC_OFFSET + MAGIC_SIZE;
+const MAX_PIECES_OFFSET: usize = VERSION_OFFSET + VERSION_SIZE;
+const ELEMENT_COUNT_OFFSET: usize = MAX_PIECES_OFFSET + MAX_PIECES_SIZE;
+const HEADER_SIZE: usize = ELEMENT_COUNT_OFFSET + ELEMENT_COUNT_SIZE;
+
+const SLOT_TAG_SIZE: usize = PieceIndex::SIZE;
+const SLOT_SIZE: usize = SLOT_TAG_SIZE + Piece::SIZE;
+
+/// Sentinel tag marking a slot as empty.
+const EMPTY_TAG: u64 = u64::MAX;
+
+#[derive(Debug)]
+struct Inner {
+    mmap: Mutex<MmapMut>,
+    max_pieces: u64,
+}
+
+/// Piece cache backed by a single preallocated, memory-mapped file with
+/// fixed-size slots, rather than one file per piece.
+///
+/// Layout: a [`HEADER_SIZE`]-byte header (magic, version, `max_pieces` and a
+/// running element count), followed by `max_pieces` fixed-size slots. Slot
+/// `i` lives at `HEADER_SIZE + i * SLOT_SIZE` and holds an 8-byte
+/// little-endian [`PieceIndex`] tag (or [`EMPTY_TAG`] if unused) followed by
+/// [`Piece::SIZE`] bytes of payload.
+#[derive(Debug, Clone)]
+pub struct MmapPieceCache {
+    inner: Arc<Inner>,
+}
+
+impl MmapPieceCache {
+    pub fn open(path: &Path, max_pieces: u64) -> Result<Self, DiskPieceCacheError> {
+        let file_size = HEADER_SIZE as u64 + max_pieces * SLOT_SIZE as u64;
+
+        if let Some(basedir) = path.parent() {
+            std::fs::create_dir_all(basedir)?;
+        }
+
+        let mut file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(path)?;
+        let is_new = file.metadata()?.len() == 0;
+
+        // Validate the existing header *before* touching the file's size or
+        // mapping it for write: resizing first and rejecting after would
+        // have already truncated a cache that was opened with a different
+        // `max_pieces` than it was created with.
+        if !is_new {
+            Self::validate_header(&mut file, max_pieces)?;
+        }
+
+        file.set_len(file_size)?;
+        let mut mmap = unsafe { MmapMut::map_mut(&file)? };
+
+        if is_new {
+            mmap[MAGIC_OFFSET..MAGIC_OFFSET + MAGIC_SIZE].copy_from_slice(&MAGIC);
+            mmap[VERSION_OFFSET..VERSION_OFFSET + VERSION_SIZE]
+                .copy_from_slice(&VERSION.to_le_bytes());
+            mmap[MAX_PIECES_OFFSET..MAX_PIECES_OFFSET + MAX_PIECES_SIZE]
+                .copy_from_slice(&max_pieces.to_le_bytes());
+            mmap[ELEMENT_COUNT_OFFSET..ELEMENT_COUNT_OFFSET + ELEMENT_COUNT_SIZE]
+                .copy_from_slice(&0u64.to_le_bytes());
+            for slot in 0..max_pieces {
+                let offset = Self::slot_offset(slot);
+                mmap[offset..offset + SLOT_TAG_SIZE].copy_from_slice(&EMPTY_TAG.to_le_bytes());
+            }
+            mmap.flush()?;
+        }
+
+        Ok(Self {
+            inner: Arc::new(Inner {
+                mmap: Mutex::new(mmap),
+                max_pieces,
+            }),
+        })
+    }
+
+    /// Read the on-disk header directly (no mmap, no resize) and check that
+    /// it was written by a compatible version of this format for the
+    /// requested `max_pieces`.
+    fn validate_header(file: &mut File, max_pieces: u64) -> Result<(), DiskPieceCacheError> {
+        let mut header = [0u8; HEADER_SIZE];
+        file.seek(SeekFrom::Start(0))?;
+        file.read_exact(&mut header)
+            .map_err(|_| DiskPieceCacheError::InvalidHeader)?;
+
+        if header[MAGIC_OFFSET..MAGIC_OFFSET + MAGIC_SIZE] != MAGIC {
+            return Err(DiskPieceCacheError::InvalidHeader);
+        }
+
+        let stored_version = u32::from_le_bytes(
+            header[VERSION_OFFSET..VERSION_OFFSET + VERSION_SIZE]
+                .try_into()
+                .expect("slice is VERSION_SIZE bytes; qed"),
+        );
+        if stored_version != VERSION {
+            return Err(DiskPieceCacheError::InvalidHeader);
+        }
+
+        let stored_max_pieces = u64::from_le_bytes(
+            header[MAX_PIECES_OFFSET..MAX_PIECES_OFFSET + MAX_PIECES_SIZE]
+                .try_into()
+                .expect("slice is MAX_PIECES_SIZE bytes; qed"),
+        );
+        if stored_max_pieces != max_pieces {
+            return Err(DiskPieceCacheError::InvalidHeader);
+        }
+
+        Ok(())
+    }
+
+    fn slot_offset(slot: u64) -> usize {
+        HEADER_SIZE + slot as usize * SLOT_SIZE
+    }
+
+    fn slot_for(&self, piece_index: PieceIndex) -> u64 {
+        u64::from(piece_index) % self.inner.max_pieces
+    }
+
+    fn bump_element_count(mmap: &mut MmapMut, delta: i64) {
+        let current = u64::from_le_bytes(
+            mmap[ELEMENT_COUNT_OFFSET..ELEMENT_COUNT_OFFSET + ELEMENT_COUNT_SIZE]
+                .try_into()
+                .expect("slice is ELEMENT_COUNT_SIZE bytes; qed"),
+        );
+        let updated = if delta.is_negative() {
+            current.saturating_sub(delta.unsigned_abs())
+        } else {
+            current + delta as u64
+        };
+        mmap[ELEMENT_COUNT_OFFSET..ELEMENT_COUNT_OFFSET + ELEMENT_COUNT_SIZE]
+            .copy_from_slice(&updated.to_le_bytes());
+    }
+
+    /// Number of occupied slots, as tracked by the header.
+    pub fn element_count(&self) -> u64 {
+        let mmap = self.inner.mmap.lock().expect("mmap mutex poisoned");
+        u64::from_le_bytes(
+            mmap[ELEMENT_COUNT_OFFSET..ELEMENT_COUNT_OFFSET + ELEMENT_COUNT_SIZE]
+                .try_into()
+                .expect("slice is ELEMENT_COUNT_SIZE bytes; qed"),
+        )
+    }
+
+    /// Write `piece` into its slot, returning the [`PieceIndex`] that
+    /// previously occupied the slot if this store evicted a *different*
+    /// piece (a slot collision, since slots are assigned `piece_index %
+    /// max_pieces`).
+    pub async fn write_piece(
+        &self,
+        piece_index: PieceIndex,
+        piece: Piece,
+    ) -> Result<Option<PieceIndex>, DiskPieceCacheError> {
+        let offset = Self::slot_offset(self.slot_for(piece_index));
+        let tag = u64::from(piece_index).to_le_bytes();
+
+        let mut mmap = self.inner.mmap.lock().expect("mmap mutex poisoned");
+        let existing_tag = u64::from_le_bytes(
+            mmap[offset..offset + SLOT_TAG_SIZE]
+                .try_into()
+                .expect("slice is SLOT_TAG_SIZE bytes; qed"),
+        );
+        mmap[offset..offset + SLOT_TAG_SIZE].copy_from_slice(&tag);
+        mmap[offset + SLOT_TAG_SIZE..offset + SLOT_SIZE].copy_from_slice(&piece.0);
+        if existing_tag == EMPTY_TAG {
+            Self::bump_element_count(&mut mmap, 1);
+        }
+        mmap.flush_range(offset, SLOT_SIZE)?;
+
+        let evicted = (existing_tag != EMPTY_TAG && existing_tag != u64::from(piece_index))
+            .then(|| PieceIndex::from(existing_tag));
+        Ok(evicted)
+    }
+
+    pub async fn read_piece(
+        &self,
+        piece_index: PieceIndex,
+    ) -> Result<Option<Piece>, DiskPieceCacheError> {
+        let offset = Self::slot_offset(self.slot_for(piece_index));
+
+        let mmap = self.inner.mmap.lock().expect("mmap mutex poisoned");
+        let tag = u64::from_le_bytes(
+            mmap[offset..offset + SLOT_TAG_SIZE]
+                .try_into()
+                .expect("slice is SLOT_TAG_SIZE bytes; qed"),
+        );
+        if tag != u64::from(piece_index) {
+            return Ok(None);
+        }
+
+        let mut piece = Piece::default();
+        piece
+            .0
+            .copy_from_slice(&mmap[offset + SLOT_TAG_SIZE..offset + SLOT_SIZE]);
+        Ok(Some(piece))
+    }
+
+    pub async fn remove_piece(&self, piece_index: PieceIndex) {
+        let offset = Self::slot_offset(self.slot_for(piece_index));
+
+        let mut mmap = self.inner.mmap.lock().expect("mmap mutex poisoned");
+        let tag = u64::from_le_bytes(
+            mmap[offset..offset + SLOT_TAG_SIZE]
+                .try_into()
+                .expect("slice is SLOT_TAG_SIZE bytes; qed"),
+        );
+        if tag == u64::from(piece_index) {
+            mmap[offset..offset + SLOT_TAG_SIZE].copy_from_slice(&EMPTY_TAG.to_le_bytes());
+            Self::bump_element_count(&mut mmap, -1);
+            let _ = mmap.flush_range(offset, SLOT_TAG_SIZE);
+        }
+    }
+
+    pub fn has_piece_sync(&self, piece_index: PieceIndex) -> bool {
+        let offset = Self::slot_offset(self.slot_for(piece_index));
+        let mmap = self.inner.mmap.lock().expect("mmap mutex poisoned");
+        let tag = u64::from_le_bytes(
+            mmap[offset..offset + SLOT_TAG_SIZE]
+                .try_into()
+                .expect("slice is SLOT_TAG_SIZE bytes; qed"),
+        );
+        tag == u64::from(piece_index)
+    }
+
+    pub async fn has_piece(&self, piece_index: PieceIndex) -> bool {
+        self.has_piece_sync(piece_index)
+    }
+}
+
+impl crate::DiskCache<PieceIndex, Piece> for MmapPieceCache {
+    type Error = DiskPieceCacheError;
+
+    fn load(
+        &self,
+        key: &PieceIndex,
+    ) -> impl std::future::Future<Output = Result<Option<Piece>, Self::Error>> + Send {
+        self.read_piece(*key)
+    }
+
+    fn store(
+        &mut self,
+        key: &PieceIndex,
+        value: Piece,
+    ) -> impl std::future::Future<Output = Result<Option<PieceIndex>, Self::Error>> + Send {
+        self.write_piece(*key, value)
+    }
+
+    fn remove(
+        &mut self,
+        key: &PieceIndex,
+    ) -> impl std::future::Future<Output = Result<(), Self::Error>> + Send {
+        let piece_index = *key;
+        async move {
+            self.remove_piece(piece_index).await;
+            Ok(())
+        }
+    }
+
+    fn exist(&self, key: &PieceIndex) -> impl std::future::Future<Output = bool> + Send {
+        self.has_piece(*key)
+    }
+
+    fn exist_sync(&self, key: &PieceIndex) -> bool {
+        self.has_piece_sync(*key)
+    }
+
+    fn directory(&self) -> &Path {
+        // Single-file backend; there is no directory to report.
+        Path::new(".")
+    }
+
+    fn keys(&self) -> impl std::future::Future<Output = Vec<PieceIndex>> + Send {
+        let inner = Arc::clone(&self.inner);
+        async move {
+            tokio::task::spawn_blocking(move || {
+                let mmap = inner.mmap.lock().expect("mmap mutex poisoned");
+                (0..inner.max_pieces)
+                    .filter_map(|slot| {
+                        let offset = MmapPieceCache::slot_offset(slot);
+                        let tag = u64::from_le_bytes(
+                            mmap[offset..offset + SLOT_TAG_SIZE]
+                                .try_into()
+                                .expect("slice is SLOT_TAG_SIZE bytes; qed"),
+                        );
+                        (tag != EMPTY_TAG).then(|| PieceIndex::from(tag))
+                    })
+                    .collect()
+            })
+            .await
+            .unwrap_or_default()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::{SystemTime, UNIX_EPOCH};
+
+    fn temp_path(name: &str) -> std::path::PathBuf {
+        let nanos = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .expect("system clock is after the epoch")
+            .as_nanos();
+        std::env::temp_dir().join(format!("cache-syncer-mmap-{name}-{nanos}"))
+    }
+
+    fn piece(byte: u8) -> Piece {
+        Piece(vec![byte; Piece::SIZE])
+    }
+
+    #[tokio::test]
+    async fn round_trips_a_piece_through_reopen() {
+        let path = temp_path("round-trip");
+        let index = PieceIndex::from(1);
+
+        let cache = MmapPieceCache::open(&path, 4).unwrap();
+        cache.write_piece(index, piece(7)).await.unwrap();
+        assert_eq!(cache.read_piece(index).await.unwrap(), Some(piece(7)));
+        drop(cache);
+
+        let reopened = MmapPieceCache::open(&path, 4).unwrap();
+        assert_eq!(reopened.read_piece(index).await.unwrap(), Some(piece(7)));
+
+        std::fs::remove_file(&path).ok();
+    }
+
+    #[tokio::test]
+    async fn rejects_reopen_with_a_different_max_pieces_without_truncating() {
+        let path = temp_path("mismatch");
+        let index = PieceIndex::from(1);
+
+        let cache = MmapPieceCache::open(&path, 4).unwrap();
+        cache.write_piece(index, piece(9)).await.unwrap();
+        drop(cache);
+
+        let mismatched = MmapPieceCache::open(&path, 8);
+        assert!(matches!(
+            mismatched,
+            Err(DiskPieceCacheError::InvalidHeader)
+        ));
+
+        // The rejected reopen must not have touched the file: reopening
+        // with the original `max_pieces` still finds the piece.
+        let reopened = MmapPieceCache::open(&path, 4).unwrap();
+        assert_eq!(reopened.read_piece(index).await.unwrap(), Some(piece(9)));
+
+        std::fs::remove_file(&path).ok();
+    }
+}